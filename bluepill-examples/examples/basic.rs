@@ -44,7 +44,7 @@ fn main() -> ! {
     };
 
     let spi = Spi::spi2(dp.SPI2, (spi2_sck, NoMiso, spi2_mosi), spi_mode, 100.khz(), clocks, &mut rcc.apb1);
-    let mut dac = AD5668::new(spi, spi2_cs);
+    let mut dac = AD5668::new(spi, spi2_cs).unwrap();
 
     loop {
         dac.write_and_update_dac_channel(Address::AllDacs, 0xffff).ok();
@@ -43,7 +43,7 @@ fn main() -> ! {
         clocks,
         &mut rcc.apb1,
     );
-    let mut dac = AD5668::new(spi, spi2_cs);
+    let mut dac = AD5668::new(spi, spi2_cs).unwrap();
 
     dac.enable_internal_ref();
 
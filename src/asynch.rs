@@ -0,0 +1,296 @@
+//! Async driver built on `embedded-hal-async`'s `SpiDevice` trait. Enabled with the `async`
+//! feature. Mirrors the `eh1` driver but every command is `.await`able, so generating waveforms
+//! does not block other tasks on an Embassy or RTIC executor.
+use crate::{
+    encode_update_command, Address, Channels, ClearCode, Command, InternalRef, PowerDownMode,
+    Voltage,
+};
+use embedded_hal_async::spi::SpiDevice;
+
+/// Async AD5668 DAC driver. Wraps an `embedded-hal-async` `SpiDevice` to send commands to an
+/// AD5668 without blocking the executor
+pub struct AD5668<SPI> {
+    spi: SPI,
+}
+
+impl<SPI> AD5668<SPI>
+where
+    SPI: SpiDevice,
+{
+    /// Construct a new async AD5668 driver around an `SpiDevice`. Chip select is managed by `SPI`
+    pub fn new(spi: SPI) -> Self {
+        Self { spi }
+    }
+
+    /// Helper function that writes a single frame as one chip-select-managed SPI transaction
+    async fn write_spi(&mut self, data: &[u8]) -> Result<(), SPI::Error> {
+        self.spi.write(data).await
+    }
+
+    /// Write input register for the dac at address with the value, does not update dac register yet
+    pub async fn write_input_register(
+        &mut self,
+        address: Address,
+        value: u16,
+    ) -> Result<(), SPI::Error> {
+        self.write_spi(&encode_update_command(
+            Command::WriteInputRegister,
+            address,
+            value,
+        ))
+        .await
+    }
+
+    /// Update dac register for the dac at address
+    pub async fn update_dac_register(
+        &mut self,
+        address: Address,
+        value: u16,
+    ) -> Result<(), SPI::Error> {
+        self.write_spi(&encode_update_command(
+            Command::UpdateDacRegister,
+            address,
+            value,
+        ))
+        .await
+    }
+
+    /// Write to a single input register, then update all dac channels. This can be used as the last
+    /// command when updating multiple DACs. First stage values for all DACs then update them
+    /// simultaniously by performing the last write using this command
+    pub async fn write_input_register_update_all(
+        &mut self,
+        address: Address,
+        value: u16,
+    ) -> Result<(), SPI::Error> {
+        self.write_spi(&encode_update_command(
+            Command::WriteInputUpdateAll,
+            address,
+            value,
+        ))
+        .await
+    }
+
+    /// Write to input register and then update the dac register in one command.
+    pub async fn write_and_update_dac_channel(
+        &mut self,
+        address: Address,
+        value: u16,
+    ) -> Result<(), SPI::Error> {
+        self.write_spi(&encode_update_command(
+            Command::WriteUpdateDacChannel,
+            address,
+            value,
+        ))
+        .await
+    }
+
+    /// Enable the internal reference
+    pub async fn enable_internal_ref(&mut self) -> Result<(), SPI::Error> {
+        self.write_spi(&[
+            Command::SetInternalRefRegister as u8,
+            0x00u8,
+            0x00u8,
+            InternalRef::Enabled as u8,
+        ])
+        .await
+    }
+
+    /// Disable the internal reference
+    pub async fn disable_internal_ref(&mut self) -> Result<(), SPI::Error> {
+        self.write_spi(&[
+            Command::SetInternalRefRegister as u8,
+            0x00u8,
+            0x00u8,
+            InternalRef::Disabled as u8,
+        ])
+        .await
+    }
+
+    /// Reset the DAC
+    pub async fn reset(&mut self) -> Result<(), SPI::Error> {
+        self.write_spi(&[Command::Reset as u8, 0x00u8, 0x00u8, 0x00u8])
+            .await
+    }
+
+    /// Write a pre-encoded buffer of commands (see [`crate::encode_sequence`]) in a single
+    /// chip-select-managed SPI transaction, e.g. a DMA-backed waveform table
+    pub async fn write_buffer(&mut self, buf: &[u8]) -> Result<(), SPI::Error> {
+        self.write_spi(buf).await
+    }
+
+    /// Set the power-down mode for the selected channels, controlling the output impedance while
+    /// they are powered down
+    pub async fn power_down(
+        &mut self,
+        channels: Channels,
+        mode: PowerDownMode,
+    ) -> Result<(), SPI::Error> {
+        self.write_spi(&[
+            Command::PowerDACUpDown as u8,
+            0x00u8,
+            mode as u8,
+            channels.bits(),
+        ])
+        .await
+    }
+
+    /// Power the selected channels back up to normal operation
+    pub async fn power_up(&mut self, channels: Channels) -> Result<(), SPI::Error> {
+        self.power_down(channels, PowerDownMode::Normal).await
+    }
+
+    /// Set the LDAC mask. A set bit makes that channel update immediately on a write, bypassing
+    /// the hardware LDAC pin
+    pub async fn set_ldac_mask(&mut self, channels: Channels) -> Result<(), SPI::Error> {
+        self.write_spi(&[
+            Command::LoadLDACRegister as u8,
+            0x00u8,
+            0x00u8,
+            channels.bits(),
+        ])
+        .await
+    }
+
+    /// Set what value the CLR pin loads into the DAC registers
+    pub async fn set_clear_code(&mut self, code: ClearCode) -> Result<(), SPI::Error> {
+        self.write_spi(&[
+            Command::LoadClearCodeRegister as u8,
+            0x00u8,
+            0x00u8,
+            code as u8,
+        ])
+        .await
+    }
+
+    /// Destroy the driver and return the wrapped `SpiDevice` to be re-used
+    pub fn destroy(self) -> SPI {
+        self.spi
+    }
+}
+
+impl<SPI> Voltage<AD5668<SPI>>
+where
+    SPI: SpiDevice,
+{
+    /// Write and update the channel at `address` to the DAC code closest to `voltage`, clamped to
+    /// the rails
+    pub async fn set_voltage(&mut self, address: Address, voltage: f32) -> Result<(), SPI::Error> {
+        let code = self.code_for(voltage);
+        self.dac.write_and_update_dac_channel(address, code).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_hal_mock::eh1::spi::{Mock, Transaction};
+
+    extern crate std;
+    use std::vec;
+
+    // A single `SpiDevice::write` call is one bracketed transaction: start, the write itself, end
+    fn write_transaction(data: std::vec::Vec<u8>) -> std::vec::Vec<Transaction<u8>> {
+        vec![
+            Transaction::transaction_start(),
+            Transaction::write_vec(data),
+            Transaction::transaction_end(),
+        ]
+    }
+
+    #[test]
+    pub fn should_enable_internal_ref() {
+        let spi = Mock::new(&write_transaction(vec![0x08u8, 0x00u8, 0x00u8, 0x01u8]));
+
+        let mut dac = AD5668::new(spi);
+
+        pollster::block_on(dac.enable_internal_ref()).unwrap();
+        dac.destroy().done();
+    }
+
+    #[test]
+    pub fn should_disable_internal_ref() {
+        let spi = Mock::new(&write_transaction(vec![0x08u8, 0x00u8, 0x00u8, 0x00u8]));
+
+        let mut dac = AD5668::new(spi);
+
+        pollster::block_on(dac.disable_internal_ref()).unwrap();
+        dac.destroy().done();
+    }
+
+    #[test]
+    pub fn should_send_reset_command() {
+        let spi = Mock::new(&write_transaction(vec![0x07u8, 0x00u8, 0x00u8, 0x00u8]));
+
+        let mut dac = AD5668::new(spi);
+
+        pollster::block_on(dac.reset()).unwrap();
+        dac.destroy().done();
+    }
+
+    #[test]
+    pub fn should_power_down_channels() {
+        let spi = Mock::new(&write_transaction(vec![
+            0x04u8, 0x00u8, 0b10u8, 0b0000_0101u8,
+        ]));
+
+        let mut dac = AD5668::new(spi);
+
+        pollster::block_on(dac.power_down(Channels::DAC_A | Channels::DAC_C, PowerDownMode::GroundViaHundredK))
+            .unwrap();
+        dac.destroy().done();
+    }
+
+    #[test]
+    pub fn should_power_up_channels() {
+        let spi = Mock::new(&write_transaction(vec![0x04u8, 0x00u8, 0x00u8, 0xffu8]));
+
+        let mut dac = AD5668::new(spi);
+
+        pollster::block_on(dac.power_up(Channels::ALL)).unwrap();
+        dac.destroy().done();
+    }
+
+    #[test]
+    pub fn should_set_ldac_mask() {
+        let spi = Mock::new(&write_transaction(vec![
+            0x06u8, 0x00u8, 0x00u8, 0b0000_0001u8,
+        ]));
+
+        let mut dac = AD5668::new(spi);
+
+        pollster::block_on(dac.set_ldac_mask(Channels::DAC_A)).unwrap();
+        dac.destroy().done();
+    }
+
+    #[test]
+    pub fn should_set_clear_code() {
+        let spi = Mock::new(&write_transaction(vec![0x05u8, 0x00u8, 0x00u8, 0b01u8]));
+
+        let mut dac = AD5668::new(spi);
+
+        pollster::block_on(dac.set_clear_code(ClearCode::Midscale)).unwrap();
+        dac.destroy().done();
+    }
+
+    #[test]
+    pub fn should_write_buffer() {
+        let spi = Mock::new(&write_transaction(vec![0x03u8, 0x00u8, 0x00u8, 0x00u8]));
+
+        let mut dac = AD5668::new(spi);
+
+        pollster::block_on(dac.write_buffer(&[0x03u8, 0x00u8, 0x00u8, 0x00u8])).unwrap();
+        dac.destroy().done();
+    }
+
+    #[test]
+    pub fn should_set_voltage() {
+        let spi = Mock::new(&write_transaction(vec![0x03u8, 0x08u8, 0x00u8, 0x00u8]));
+
+        let dac = AD5668::new(spi);
+        let mut dac = crate::Voltage::new(dac, crate::Vref::INTERNAL_2V5);
+
+        pollster::block_on(dac.set_voltage(Address::DacA, 1.25)).unwrap();
+        dac.destroy().destroy().done();
+    }
+}
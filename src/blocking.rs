@@ -0,0 +1,352 @@
+//! The original embedded-hal 0.2 driver. Used when the `eh1` feature is not enabled. This driver
+//! owns the chip select pin directly and toggles it around every SPI transaction.
+use crate::{
+    encode_update_command, Address, Channels, ClearCode, Command, InternalRef, PowerDownMode,
+    Voltage,
+};
+use embedded_hal::{blocking::spi::Write, digital::v2::OutputPin};
+
+/// AD5668 DAC driver. Wraps an SPI port and a chip select pin to send commands to an AD5668
+pub struct AD5668<SPI, CS> {
+    spi: SPI,
+    chip_select: CS,
+}
+
+/// Error writing to the AD5668, either an SPI transfer error or a chip select pin error
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Error<SpiErr, PinErr> {
+    /// An error occurred while writing to the SPI bus
+    Spi(SpiErr),
+    /// An error occurred while driving the chip select pin
+    Pin(PinErr),
+}
+
+impl<SPI, CS, E, PE> AD5668<SPI, CS>
+where
+    SPI: Write<u8, Error = E>,
+    CS: OutputPin<Error = PE>,
+{
+    /// Construct a new AD5668 driver
+    pub fn new(spi: SPI, mut chip_select: CS) -> Result<Self, Error<E, PE>> {
+        // Init chip select high
+        chip_select.set_high().map_err(Error::Pin)?;
+
+        Ok(Self { spi, chip_select })
+    }
+
+    /// Helper function that handles writing to the SPI bus while toggeling chip select
+    fn write_spi(&mut self, data: &[u8]) -> Result<(), Error<E, PE>> {
+        self.chip_select.set_low().map_err(Error::Pin)?;
+        let result = self.spi.write(data).map_err(Error::Spi);
+        self.chip_select.set_high().map_err(Error::Pin)?;
+        result
+    }
+
+    /// Write input register for the dac at address with the value, does not update dac register yet
+    pub fn write_input_register(
+        &mut self,
+        address: Address,
+        value: u16,
+    ) -> Result<(), Error<E, PE>> {
+        self.write_spi(&encode_update_command(
+            Command::WriteInputRegister,
+            address,
+            value,
+        ))
+    }
+
+    /// Update dac register for the dac at address
+    /// TODO: Check if the data is written too or if this just updates data written earlier to the
+    ///       dac
+    pub fn update_dac_register(
+        &mut self,
+        address: Address,
+        value: u16,
+    ) -> Result<(), Error<E, PE>> {
+        self.write_spi(&encode_update_command(
+            Command::UpdateDacRegister,
+            address,
+            value,
+        ))
+    }
+
+    /// Write to a single input register, then update all dac channels. This can be used as the last
+    /// command when updating multiple DACs. First stage values for all DACs then update them
+    /// simultaniously by performing the last write using this command
+    pub fn write_input_register_update_all(
+        &mut self,
+        address: Address,
+        value: u16,
+    ) -> Result<(), Error<E, PE>> {
+        self.write_spi(&encode_update_command(
+            Command::WriteInputUpdateAll,
+            address,
+            value,
+        ))
+    }
+
+    /// Write to input register and then update the dac register in one command.
+    pub fn write_and_update_dac_channel(
+        &mut self,
+        address: Address,
+        value: u16,
+    ) -> Result<(), Error<E, PE>> {
+        self.write_spi(&encode_update_command(
+            Command::WriteUpdateDacChannel,
+            address,
+            value,
+        ))
+    }
+
+    /// Enable the internal reference
+    pub fn enable_internal_ref(&mut self) -> Result<(), Error<E, PE>> {
+        self.write_spi(&[
+            Command::SetInternalRefRegister as u8,
+            0x00u8,
+            0x00u8,
+            InternalRef::Enabled as u8,
+        ])
+    }
+
+    /// Disable the internal reference
+    pub fn disable_internal_ref(&mut self) -> Result<(), Error<E, PE>> {
+        self.write_spi(&[
+            Command::SetInternalRefRegister as u8,
+            0x00u8,
+            0x00u8,
+            InternalRef::Disabled as u8,
+        ])
+    }
+
+    /// Reset the DAC
+    pub fn reset(&mut self) -> Result<(), Error<E, PE>> {
+        self.write_spi(&[Command::Reset as u8, 0x00u8, 0x00u8, 0x00u8])
+    }
+
+    /// Write a pre-encoded buffer of commands (see [`crate::encode_sequence`]) in a single
+    /// chip-select-low SPI transaction, e.g. a DMA-backed waveform table
+    pub fn write_buffer(&mut self, buf: &[u8]) -> Result<(), Error<E, PE>> {
+        self.write_spi(buf)
+    }
+
+    /// Set the power-down mode for the selected channels, controlling the output impedance while
+    /// they are powered down
+    pub fn power_down(
+        &mut self,
+        channels: Channels,
+        mode: PowerDownMode,
+    ) -> Result<(), Error<E, PE>> {
+        self.write_spi(&[
+            Command::PowerDACUpDown as u8,
+            0x00u8,
+            mode as u8,
+            channels.bits(),
+        ])
+    }
+
+    /// Power the selected channels back up to normal operation
+    pub fn power_up(&mut self, channels: Channels) -> Result<(), Error<E, PE>> {
+        self.power_down(channels, PowerDownMode::Normal)
+    }
+
+    /// Set the LDAC mask. A set bit makes that channel update immediately on a write, bypassing
+    /// the hardware LDAC pin
+    pub fn set_ldac_mask(&mut self, channels: Channels) -> Result<(), Error<E, PE>> {
+        self.write_spi(&[
+            Command::LoadLDACRegister as u8,
+            0x00u8,
+            0x00u8,
+            channels.bits(),
+        ])
+    }
+
+    /// Set what value the CLR pin loads into the DAC registers
+    pub fn set_clear_code(&mut self, code: ClearCode) -> Result<(), Error<E, PE>> {
+        self.write_spi(&[
+            Command::LoadClearCodeRegister as u8,
+            0x00u8,
+            0x00u8,
+            code as u8,
+        ])
+    }
+
+    /// Destroy the driver and return the wrapped SPI driver and chip select pin to be re-used
+    pub fn destroy(self) -> (SPI, CS) {
+        (self.spi, self.chip_select)
+    }
+}
+
+impl<SPI, CS, E, PE> Voltage<AD5668<SPI, CS>>
+where
+    SPI: Write<u8, Error = E>,
+    CS: OutputPin<Error = PE>,
+{
+    /// Write and update the channel at `address` to the DAC code closest to `voltage`, clamped to
+    /// the rails
+    pub fn set_voltage(&mut self, address: Address, voltage: f32) -> Result<(), Error<E, PE>> {
+        let code = self.code_for(voltage);
+        self.dac.write_and_update_dac_channel(address, code)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_hal_mock::eh0::{digital as pin, spi};
+
+    extern crate std;
+    use std::vec;
+
+    // Default cs expectations: `new` sets high, then each command toggles low, then high
+    fn setup_mocks(spi_expectations: &[spi::Transaction]) -> (spi::Mock, pin::Mock) {
+        let spi = spi::Mock::new(spi_expectations);
+
+        let chip_select = pin::Mock::new(&[
+            pin::Transaction::set(pin::State::High),
+            pin::Transaction::set(pin::State::Low),
+            pin::Transaction::set(pin::State::High),
+        ]);
+
+        (spi, chip_select)
+    }
+
+    #[test]
+    pub fn should_init_chip_select_high() {
+        let mut spi = spi::Mock::new(&[]);
+        let mut chip_select = pin::Mock::new(&[pin::Transaction::set(pin::State::High)]);
+
+        let _dac = AD5668::new(spi.clone(), chip_select.clone()).unwrap();
+
+        spi.done();
+        chip_select.done();
+    }
+
+    #[test]
+    pub fn should_enable_internal_ref() {
+        let (mut spi, mut chip_select) = setup_mocks(&[spi::Transaction::write(vec![
+            0x08u8, 0x00u8, 0x00u8, 0x01u8,
+        ])]);
+
+        let mut dac = AD5668::new(spi.clone(), chip_select.clone()).unwrap();
+
+        dac.enable_internal_ref().unwrap();
+
+        spi.done();
+        chip_select.done();
+    }
+
+    #[test]
+    pub fn should_disable_internal_ref() {
+        let (mut spi, mut chip_select) = setup_mocks(&[spi::Transaction::write(vec![
+            0x08u8, 0x00u8, 0x00u8, 0x00u8,
+        ])]);
+
+        let mut dac = AD5668::new(spi.clone(), chip_select.clone()).unwrap();
+
+        dac.disable_internal_ref().unwrap();
+
+        spi.done();
+        chip_select.done();
+    }
+
+    #[test]
+    pub fn should_send_reset_command() {
+        let (mut spi, mut chip_select) = setup_mocks(&[spi::Transaction::write(vec![
+            0x07u8, 0x00u8, 0x00u8, 0x00u8,
+        ])]);
+
+        let mut dac = AD5668::new(spi.clone(), chip_select.clone()).unwrap();
+
+        dac.reset().unwrap();
+
+        spi.done();
+        chip_select.done();
+    }
+
+    #[test]
+    pub fn should_power_down_channels() {
+        let (mut spi, mut chip_select) = setup_mocks(&[spi::Transaction::write(vec![
+            0x04u8, 0x00u8, 0b10u8, 0b0000_0101u8,
+        ])]);
+
+        let mut dac = AD5668::new(spi.clone(), chip_select.clone()).unwrap();
+
+        dac.power_down(Channels::DAC_A | Channels::DAC_C, PowerDownMode::GroundViaHundredK)
+            .unwrap();
+
+        spi.done();
+        chip_select.done();
+    }
+
+    #[test]
+    pub fn should_power_up_channels() {
+        let (mut spi, mut chip_select) = setup_mocks(&[spi::Transaction::write(vec![
+            0x04u8, 0x00u8, 0x00u8, 0xffu8,
+        ])]);
+
+        let mut dac = AD5668::new(spi.clone(), chip_select.clone()).unwrap();
+
+        dac.power_up(Channels::ALL).unwrap();
+
+        spi.done();
+        chip_select.done();
+    }
+
+    #[test]
+    pub fn should_set_ldac_mask() {
+        let (mut spi, mut chip_select) = setup_mocks(&[spi::Transaction::write(vec![
+            0x06u8, 0x00u8, 0x00u8, 0b0000_0001u8,
+        ])]);
+
+        let mut dac = AD5668::new(spi.clone(), chip_select.clone()).unwrap();
+
+        dac.set_ldac_mask(Channels::DAC_A).unwrap();
+
+        spi.done();
+        chip_select.done();
+    }
+
+    #[test]
+    pub fn should_set_clear_code() {
+        let (mut spi, mut chip_select) = setup_mocks(&[spi::Transaction::write(vec![
+            0x05u8, 0x00u8, 0x00u8, 0b01u8,
+        ])]);
+
+        let mut dac = AD5668::new(spi.clone(), chip_select.clone()).unwrap();
+
+        dac.set_clear_code(ClearCode::Midscale).unwrap();
+
+        spi.done();
+        chip_select.done();
+    }
+
+    #[test]
+    pub fn should_set_voltage() {
+        let (mut spi, mut chip_select) = setup_mocks(&[spi::Transaction::write(vec![
+            0x03u8, 0x08u8, 0x00u8, 0x00u8,
+        ])]);
+
+        let dac = AD5668::new(spi.clone(), chip_select.clone()).unwrap();
+        let mut dac = crate::Voltage::new(dac, crate::Vref::INTERNAL_2V5);
+
+        dac.set_voltage(Address::DacA, 1.25).unwrap();
+
+        spi.done();
+        chip_select.done();
+    }
+
+    #[test]
+    pub fn should_clamp_voltage_to_rails() {
+        let (mut spi, mut chip_select) = setup_mocks(&[spi::Transaction::write(vec![
+            0x03u8, 0x0fu8, 0xffu8, 0xf0u8,
+        ])]);
+
+        let dac = AD5668::new(spi.clone(), chip_select.clone()).unwrap();
+        let mut dac = crate::Voltage::new(dac, crate::Vref::INTERNAL_2V5);
+
+        dac.set_voltage(Address::DacA, 100.0).unwrap();
+
+        spi.done();
+        chip_select.done();
+    }
+}
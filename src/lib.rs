@@ -5,27 +5,90 @@
 //! It supports sending commands to a AD5668 DAC over SPI.
 //!
 //! First you create an instance of the driver wrapping the SPI port the DAC is connected to;
-//! ```
-//! # use embedded_hal_mock::{spi, pin};
-//! # use ad5668::*;
-//! # let mut spi = spi::Mock::new(&[]);
-//! # let mut chip_select = pin::Mock::new(&[pin::Transaction::set(pin::State::High)]);
-//! let mut dac = AD5668::new(spi, chip_select);
-//! ```
+#![cfg_attr(
+    not(feature = "eh1"),
+    doc = r#"
+```
+# use embedded_hal_mock::eh0::{spi, digital as pin};
+# use ad5668::*;
+# let spi = spi::Mock::new(&[]);
+# let chip_select = pin::Mock::new(&[pin::Transaction::set(pin::State::High)]);
+# let (mut spi_check, mut cs_check) = (spi.clone(), chip_select.clone());
+let mut dac = AD5668::new(spi, chip_select).unwrap();
+# spi_check.done();
+# cs_check.done();
+```
+"#
+)]
+#![cfg_attr(
+    feature = "eh1",
+    doc = r#"
+```
+# use embedded_hal_mock::eh1::spi::Mock;
+# use ad5668::*;
+# let spi = Mock::new(&[]);
+# let mut spi_check = spi.clone();
+let mut dac = AD5668::new(spi);
+# spi_check.done();
+```
+"#
+)]
 //!
 //! Now commands can be sent to the DAC, for example to set all outputs high
-//! ```
-//! # use embedded_hal_mock::{spi, pin};
-//! # use ad5668::*;
-//! # let mut spi = spi::Mock::new(&[spi::Transaction::write(vec![0x02, 0xff, 0xff, 0xf0]),]);
-//! # let mut chip_select = pin::Mock::new(&[
-//! #     pin::Transaction::set(pin::State::High),
-//! #     pin::Transaction::set(pin::State::Low),
-//! #     pin::Transaction::set(pin::State::High),
-//! # ]);
-//! # let mut dac = AD5668::new(spi, chip_select);
-//! dac.write_input_register_update_all(Address::AllDacs, 0xffff);
-//! ```
+#![cfg_attr(
+    not(feature = "eh1"),
+    doc = r#"
+```
+# use embedded_hal_mock::eh0::{spi, digital as pin};
+# use ad5668::*;
+# let spi = spi::Mock::new(&[spi::Transaction::write(vec![0x02, 0xff, 0xff, 0xf0]),]);
+# let chip_select = pin::Mock::new(&[
+#     pin::Transaction::set(pin::State::High),
+#     pin::Transaction::set(pin::State::Low),
+#     pin::Transaction::set(pin::State::High),
+# ]);
+# let (mut spi_check, mut cs_check) = (spi.clone(), chip_select.clone());
+# let mut dac = AD5668::new(spi, chip_select).unwrap();
+dac.write_input_register_update_all(Address::AllDacs, 0xffff);
+# spi_check.done();
+# cs_check.done();
+```
+"#
+)]
+#![cfg_attr(
+    feature = "eh1",
+    doc = r#"
+```
+# use embedded_hal_mock::eh1::spi::{Mock, Transaction};
+# use ad5668::*;
+# let spi = Mock::new(&[
+#     Transaction::transaction_start(),
+#     Transaction::write_vec(vec![0x02, 0xff, 0xff, 0xf0]),
+#     Transaction::transaction_end(),
+# ]);
+# let mut spi_check = spi.clone();
+# let mut dac = AD5668::new(spi);
+dac.write_input_register_update_all(Address::AllDacs, 0xffff);
+# spi_check.done();
+```
+"#
+)]
+//!
+//! ## embedded-hal 1.0
+//! By default this crate targets embedded-hal 0.2 and owns the chip select pin itself. Enabling
+//! the `eh1` feature switches `AD5668` to wrap an embedded-hal 1.0 `SpiDevice` instead, so CS
+//! sequencing is handled by the `SpiDevice` implementation (for example a shared-bus manager).
+//! The two are mutually exclusive; existing 0.2 based projects stay on the default path.
+//! On the 0.2 path `new` and every command now return [`Error`], which carries chip select pin
+//! errors alongside SPI errors instead of silently dropping them.
+//!
+//! ## Async
+//! Enabling the `async` feature adds the [`asynch`] module, an `.await`able driver built on
+//! `embedded-hal-async`'s `SpiDevice` for use from Embassy or RTIC executors.
+//!
+//! ## Voltages
+//! Wrap a driver in [`Voltage`] with a [`Vref`] to call `set_voltage` with a requested output
+//! voltage instead of working out the DAC code yourself.
 //!
 //! ## More information
 //! - [AD5668 datasheet](https://www.analog.com/media/en/technical-documentation/data-sheets/AD5628_5648_5668.pdf)
@@ -35,118 +98,126 @@
 
 #![no_std]
 #[warn(missing_debug_implementations, missing_docs)]
-use embedded_hal::{blocking::spi::Write, digital::v2::OutputPin};
+#[cfg(not(feature = "eh1"))]
+mod blocking;
+#[cfg(feature = "eh1")]
+mod eh1;
+#[cfg(feature = "async")]
+pub mod asynch;
+
+#[cfg(not(feature = "eh1"))]
+pub use blocking::{Error, AD5668};
+#[cfg(feature = "eh1")]
+pub use eh1::AD5668;
 
-/// AD5668 DAC driver. Wraps an I2C port to send commands to an AD5668
-pub struct AD5668<SPI, CS> {
-    spi: SPI,
-    chip_select: CS,
+/// Encodes one of the commands that updates a 16 bit value
+fn encode_update_command(command: Command, address: Address, value: u16) -> [u8; 4] {
+    [
+        command as u8,
+        ((address as u8) << 4) + (value >> 12) as u8,
+        (value >> 4) as u8,
+        (value << 4) as u8,
+    ]
 }
 
-impl<SPI, CS, E> AD5668<SPI, CS>
-where
-    SPI: Write<u8, Error = E>,
-    CS: OutputPin,
-{
-    /// Construct a new AD5668 driver
-    pub fn new(spi: SPI, mut chip_select: CS) -> Self {
-        // Init chip select high
-        chip_select.set_high().ok();
-
-        Self { spi, chip_select }
-    }
-
-    /// Helper function that handles writing to the SPI bus while toggeling chip select
-    fn write_spi(&mut self, data: &[u8]) -> Result<(), E> {
-        self.chip_select.set_low().ok();
-        let result = self.spi.write(data);
-        self.chip_select.set_high().ok();
-        result
+/// Encodes a batch of `write_and_update_dac_channel` commands into `buf`, one 4-byte frame per
+/// command, so the whole sequence can be clocked out as a single SPI transaction, for example
+/// from a HAL DMA transfer. Returns the filled portion of `buf`.
+///
+/// # Panics
+/// Panics if `buf` is smaller than `4 * commands.len()` bytes.
+pub fn encode_sequence<'b>(commands: &[(Address, u16)], buf: &'b mut [u8]) -> &'b [u8] {
+    let len = commands.len() * 4;
+    assert!(buf.len() >= len, "buffer too small to encode sequence");
+
+    for (frame, (address, value)) in buf[..len].chunks_exact_mut(4).zip(commands) {
+        frame.copy_from_slice(&encode_update_command(
+            Command::WriteUpdateDacChannel,
+            *address,
+            *value,
+        ));
     }
 
-    /// Write input register for the dac at address with the value, does not update dac register yet
-    pub fn write_input_register(&mut self, address: Address, value: u16) -> Result<(), E> {
-        self.write_spi(&encode_update_command(
-            Command::WriteInputRegister,
-            address,
-            value,
-        ))
-    }
+    &buf[..len]
+}
 
-    /// Update dac register for the dac at address
-    /// TODO: Check if the data is written too or if this just updates data written earlier to the
-    ///       dac
-    pub fn update_dac_register(&mut self, address: Address, value: u16) -> Result<(), E> {
-        self.write_spi(&encode_update_command(
-            Command::UpdateDacRegister,
-            address,
-            value,
-        ))
-    }
+/// Accumulates input register writes for several AD5668 devices sharing SCLK/SYNC in a
+/// daisy-chain (DIN of one device wired to SDO of the next), so they can be clocked out as a
+/// single chip-select-low transaction (e.g. with `write_buffer`). `N` is the chain's total
+/// byte capacity, `4 * number of devices`.
+///
+/// Each device only keeps the last 32 bits clocked into its shift register, so frames are kept in
+/// clock-out order: each `push` shifts previously staged frames toward the back of the buffer and
+/// inserts the new one at the front. That means the first frame pushed is clocked out last and
+/// stays in the device nearest the master (first in the chain), while the most recently pushed
+/// frame is clocked out first and ends up shifted all the way into the device farthest from the
+/// master (last in the chain).
+///
+/// [`push_and_update`](Self::push_and_update) only triggers an update on whichever single device
+/// ends up holding that frame when SYNC rises — it does not update any other device in the chain,
+/// even though their input registers may already be staged via `push`. Use it for the one device
+/// whose outputs this transaction should latch; the others keep their staged values until they
+/// each receive their own `push_and_update`.
+/// ```
+/// # use ad5668::{Address, DaisyChain};
+/// let mut chain = DaisyChain::<8>::new();
+/// chain.push(Address::DacA, 0x1111); // nearest device, clocked out last
+/// chain.push_and_update(Address::DacB, 0x2222); // farthest device, clocked out first, its outputs latch
+/// // DacB's frame (pushed last) is clocked out first, so it comes first in the buffer
+/// assert_eq!(chain.frames()[1] >> 4, Address::DacB as u8);
+/// assert_eq!(chain.frames()[5] >> 4, Address::DacA as u8);
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct DaisyChain<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
 
-    /// Write to a single input register, then update all dac channels. This can be used as the last
-    /// command when updating multiple DACs. First stage values for all DACs then update them
-    /// simultaniously by performing the last write using this command
-    pub fn write_input_register_update_all(
-        &mut self,
-        address: Address,
-        value: u16,
-    ) -> Result<(), E> {
-        self.write_spi(&encode_update_command(
-            Command::WriteInputUpdateAll,
-            address,
-            value,
-        ))
+impl<const N: usize> DaisyChain<N> {
+    /// Construct an empty daisy chain buffer
+    pub fn new() -> Self {
+        Self { buf: [0u8; N], len: 0 }
     }
 
-    /// Write to input register and then update the dac register in one command.
-    pub fn write_and_update_dac_channel(&mut self, address: Address, value: u16) -> Result<(), E> {
-        self.write_spi(&encode_update_command(
-            Command::WriteUpdateDacChannel,
-            address,
-            value,
-        ))
+    /// Stage a write to the input register of the next device down the chain
+    ///
+    /// # Panics
+    /// Panics if the chain's capacity `N` is exceeded
+    pub fn push(&mut self, address: Address, value: u16) {
+        self.push_frame(Command::WriteInputRegister, address, value);
     }
 
-    /// Enable the internal reference
-    pub fn enable_internal_ref(&mut self) -> Result<(), E> {
-        self.write_spi(&[
-            Command::SetInternalRefRegister as u8,
-            0x00u8,
-            0x00u8,
-            InternalRef::Enabled as u8,
-        ])
+    /// Stage the update-triggering frame for the next device down the chain: like [`push`](Self::push),
+    /// but also updates that single device's DAC channels from their (possibly just-staged) input
+    /// registers. This only latches the outputs of the one device that ends up holding this frame
+    /// when SYNC rises — every other device in the chain keeps whatever it received via `push`,
+    /// staged but not yet applied to its outputs
+    ///
+    /// # Panics
+    /// Panics if the chain's capacity `N` is exceeded
+    pub fn push_and_update(&mut self, address: Address, value: u16) {
+        self.push_frame(Command::WriteInputUpdateAll, address, value);
     }
 
-    /// Disable the internal reference
-    pub fn disable_internal_ref(&mut self) -> Result<(), E> {
-        self.write_spi(&[
-            Command::SetInternalRefRegister as u8,
-            0x00u8,
-            0x00u8,
-            InternalRef::Disabled as u8,
-        ])
-    }
+    fn push_frame(&mut self, command: Command, address: Address, value: u16) {
+        assert!(self.len + 4 <= N, "daisy chain buffer is full");
 
-    /// Reset the DAC
-    pub fn reset(&mut self) -> Result<(), E> {
-        self.write_spi(&[Command::Reset as u8, 0x00u8, 0x00u8, 0x00u8])
+        let frame = encode_update_command(command, address, value);
+        self.buf.copy_within(0..self.len, 4);
+        self.buf[0..4].copy_from_slice(&frame);
+        self.len += 4;
     }
 
-    /// Destroy the driver and return the wrapped SPI driver to be re-used
-    pub fn destroy(self) -> (SPI, CS) {
-        (self.spi, self.chip_select)
+    /// The accumulated frames, ready to be clocked out in one transaction
+    pub fn frames(&self) -> &[u8] {
+        &self.buf[..self.len]
     }
 }
 
-/// Encodes one of the commands that updates a 16 bit value
-fn encode_update_command(command: Command, address: Address, value: u16) -> [u8; 4] {
-    [
-        command as u8,
-        ((address as u8) << 4) + (value >> 12) as u8,
-        (value >> 4) as u8,
-        (value << 4) as u8,
-    ]
+impl<const N: usize> Default for DaisyChain<N> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -170,6 +241,122 @@ pub enum InternalRef {
     Enabled = 0x01u8,
 }
 
+/// Output impedance applied to the channels selected with [`Channels`] by `power_down`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum PowerDownMode {
+    /// Normal operation, channel is powered up
+    Normal = 0b00,
+    /// Channel output is pulled to GND through 1 kOhm
+    GroundViaOneK = 0b01,
+    /// Channel output is pulled to GND through 100 kOhm
+    GroundViaHundredK = 0b10,
+    /// Channel output is three-stated
+    ThreeState = 0b11,
+}
+
+/// A bitmask selecting one or more DAC channels, bit 0 = DAC A .. bit 7 = DAC H. Combine channels
+/// with `|`
+/// ```
+/// # use ad5668::Channels;
+/// let channels = Channels::DAC_A | Channels::DAC_C;
+/// assert_eq!(channels.bits(), 0b0000_0101);
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct Channels(u8);
+
+impl Channels {
+    pub const DAC_A: Channels = Channels(1 << 0);
+    pub const DAC_B: Channels = Channels(1 << 1);
+    pub const DAC_C: Channels = Channels(1 << 2);
+    pub const DAC_D: Channels = Channels(1 << 3);
+    pub const DAC_E: Channels = Channels(1 << 4);
+    pub const DAC_F: Channels = Channels(1 << 5);
+    pub const DAC_G: Channels = Channels(1 << 6);
+    pub const DAC_H: Channels = Channels(1 << 7);
+    pub const NONE: Channels = Channels(0);
+    pub const ALL: Channels = Channels(0xff);
+
+    /// The raw DB7-DB0 channel bitmask
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+}
+
+impl core::ops::BitOr for Channels {
+    type Output = Channels;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Channels(self.0 | rhs.0)
+    }
+}
+
+/// The full-scale output voltage reached at DAC code `0xFFFF`, used to convert requested output
+/// voltages to DAC codes with [`Voltage`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Vref(f32);
+
+impl Vref {
+    /// The internal reference configured for 1x gain, giving a 2.5V full-scale output. Pair with
+    /// `enable_internal_ref`
+    pub const INTERNAL_2V5: Vref = Vref(2.5);
+    /// The internal reference configured for 2x gain, giving a 5V full-scale output. Pair with
+    /// `enable_internal_ref`
+    pub const INTERNAL_5V: Vref = Vref(5.0);
+
+    /// An external reference driving the given full-scale output voltage
+    pub const fn external(fullscale_volts: f32) -> Self {
+        Vref(fullscale_volts)
+    }
+}
+
+/// Wraps an AD5668 driver with a configured [`Vref`] so output voltages can be requested directly
+/// instead of DAC codes
+pub struct Voltage<D> {
+    dac: D,
+    vref: Vref,
+}
+
+impl<D> Voltage<D> {
+    /// Wrap `dac` with a reference voltage used to convert requested output voltages to codes
+    pub fn new(dac: D, vref: Vref) -> Self {
+        Self { dac, vref }
+    }
+
+    /// Converts a requested output voltage to the 16-bit DAC code it is sent as, clamped to the
+    /// representable range
+    fn code_for(&self, voltage: f32) -> u16 {
+        let scaled = voltage / self.vref.0 * 65535.0;
+        // `f32::round` needs libm and isn't available in `core`; round half away from zero by
+        // hand instead. The cast to `u16` saturates, handling the rail clamping for free.
+        let rounded = if scaled >= 0.0 {
+            scaled + 0.5
+        } else {
+            scaled - 0.5
+        };
+        rounded as u16
+    }
+
+    /// Destroy the wrapper and return the wrapped driver
+    pub fn destroy(self) -> D {
+        self.dac
+    }
+}
+
+/// Value loaded into the DAC registers when the CLR pin is asserted
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum ClearCode {
+    /// Clear to 0x0000
+    Zero = 0b00,
+    /// Clear to 0x8000, midscale
+    Midscale = 0b01,
+    /// Clear to 0xFFFF, full-scale
+    FullScale = 0b10,
+    /// Ignore CLR, keep the current value
+    NoChange = 0b11,
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(u8)]
 enum Command {
@@ -187,10 +374,8 @@ enum Command {
 #[cfg(test)]
 mod test {
     use super::*;
-    use embedded_hal_mock::{pin, spi};
 
     extern crate std;
-    use std::vec;
 
     #[test]
     pub fn should_encode_command() {
@@ -216,64 +401,62 @@ mod test {
         )
     }
 
-    fn setup_mocks() -> (spi::Mock, pin::Mock) {
-        let spi = spi::Mock::new(&[]);
-
-        // Default cs expectations, new sets high, sending command toggles low, then high
-        let chip_select = pin::Mock::new(&[
-            pin::Transaction::set(pin::State::High),
-            pin::Transaction::set(pin::State::Low),
-            pin::Transaction::set(pin::State::High),
-        ]);
+    #[test]
+    pub fn should_encode_sequence() {
+        let mut buf = [0u8; 8];
 
-        (spi, chip_select)
+        assert_eq!(
+            encode_sequence(&[(Address::DacA, 0u16), (Address::DacB, 0xffffu16)], &mut buf),
+            [
+                0b00000011, 0b00000000, 0b00000000, 0b00000000, 0b00000011, 0b00011111,
+                0b11111111, 0b11110000,
+            ],
+        )
     }
 
     #[test]
-    pub fn should_init_chip_select_high() {
-        let (spi, mut chip_select) = setup_mocks();
-
-        chip_select.expect(&[pin::Transaction::set(pin::State::High)]);
+    #[should_panic(expected = "buffer too small to encode sequence")]
+    pub fn should_panic_when_buffer_too_small() {
+        let mut buf = [0u8; 4];
 
-        let _dac = AD5668::new(spi, chip_select);
+        encode_sequence(&[(Address::DacA, 0u16), (Address::DacB, 0u16)], &mut buf);
     }
 
     #[test]
-    pub fn should_enable_internal_ref() {
-        let (mut spi, chip_select) = setup_mocks();
-
-        spi.expect(&[spi::Transaction::write(vec![
-            0x08u8, 0x00u8, 0x00u8, 0x01u8,
-        ])]);
-
-        let mut dac = AD5668::new(spi, chip_select);
+    pub fn should_clock_out_last_pushed_frame_first_in_daisy_chain() {
+        let mut chain = DaisyChain::<8>::new();
+        chain.push(Address::DacA, 0u16);
+        chain.push(Address::DacB, 0u16);
 
-        dac.enable_internal_ref().unwrap();
+        assert_eq!(
+            chain.frames(),
+            [
+                0b00000000, 0b00010000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
+                0b00000000, 0b00000000,
+            ],
+        )
     }
 
     #[test]
-    pub fn should_disable_internal_ref() {
-        let (mut spi, chip_select) = setup_mocks();
-
-        spi.expect(&[spi::Transaction::write(vec![
-            0x08u8, 0x00u8, 0x00u8, 0x00u8,
-        ])]);
-
-        let mut dac = AD5668::new(spi, chip_select);
-
-        dac.disable_internal_ref().unwrap();
+    #[should_panic(expected = "daisy chain buffer is full")]
+    pub fn should_panic_when_daisy_chain_is_full() {
+        let mut chain = DaisyChain::<4>::new();
+        chain.push(Address::DacA, 0u16);
+        chain.push(Address::DacB, 0u16);
     }
 
     #[test]
-    pub fn should_send_reset_command() {
-        let (mut spi, chip_select) = setup_mocks();
+    pub fn should_encode_update_triggering_frame() {
+        let mut chain = DaisyChain::<8>::new();
+        chain.push(Address::DacA, 0u16);
+        chain.push_and_update(Address::DacB, 0u16);
 
-        spi.expect(&[spi::Transaction::write(vec![
-            0x07u8, 0x00u8, 0x00u8, 0x00u8,
-        ])]);
-
-        let mut dac = AD5668::new(spi, chip_select);
-
-        dac.reset().unwrap();
+        assert_eq!(
+            chain.frames(),
+            [
+                0b00000010, 0b00010000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
+                0b00000000, 0b00000000,
+            ],
+        )
     }
 }